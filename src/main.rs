@@ -1,17 +1,26 @@
 extern crate sha1;
 #[macro_use]
 extern crate structopt;
+extern crate flate2;
 extern crate num_cpus;
 
 use std::cmp;
 use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
 use structopt::StructOpt;
 
 #[derive(Clone)]
@@ -89,6 +98,99 @@ impl Commit {
 
         return m.digest();
     }
+
+    // Builds the SHA1 state for the constant leading bytes of `annotate`:
+    // `commit <len>\0` + metadata + `\n` + prefix + ` `. This is only
+    // constant within a fixed digit-count bucket, since `len` depends on
+    // `base_10_length(nonce)` — callers must rebuild it whenever the
+    // nonce crosses a power-of-ten boundary.
+    fn base_hasher(&self, nonce: u64) -> sha1::Sha1 {
+        let mut m = sha1::Sha1::new();
+
+        m.update(format!("commit {}\0", self.prefix_length(nonce)).as_bytes());
+        m.update(self.metadata.as_slice());
+        m.update(b"\n");
+        m.update(self.prefix.as_slice());
+        m.update(b" ");
+
+        return m;
+    }
+
+    // Same digest as `annotate(nonce)`; `base` must come from `base_hasher`
+    // for a nonce in the same digit-count bucket as `nonce`.
+    fn annotate_from_base(&self, base: &sha1::Sha1, nonce: u64) -> sha1::Digest {
+        let mut m = base.clone();
+
+        m.update(format!("{0}\n\n", nonce).as_bytes());
+        m.update(self.message.as_slice());
+
+        return m.digest();
+    }
+
+    // The exact byte stream that `annotate` hashes, i.e. the loose object
+    // body git would write for this nonce.
+    fn object_bytes(&self, nonce: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.prefix_length(nonce) + 1 + self.message.len());
+
+        bytes.extend_from_slice(format!("commit {}\0", self.prefix_length(nonce)).as_bytes());
+        bytes.extend_from_slice(self.metadata.as_slice());
+        bytes.push(b'\n');
+        bytes.extend_from_slice(self.prefix.as_slice());
+        bytes.extend_from_slice(format!(" {0}\n\n", nonce).as_bytes());
+        bytes.extend_from_slice(self.message.as_slice());
+
+        return bytes;
+    }
+
+    fn padding_for(n: u64) -> Vec<u8> {
+        // Buckets `n` by explicit length (mirrors `base_hasher`'s digit
+        // buckets), so every combination of a given length is reachable,
+        // not just the half whose top bit is 1.
+        let mut len = 1u32;
+        let mut base = 0u64;
+
+        while n - base >= 1 << len {
+            base += 1 << len;
+            len += 1;
+        }
+
+        let k = n - base;
+
+        (0..len)
+            .rev()
+            .map(|i| if (k >> i) & 1 == 1 { b'\t' } else { b' ' })
+            .collect()
+    }
+
+    // Appends invisible trailing whitespace instead of a visible nonce
+    // line, so the stored message looks normal but the SHA1 input still
+    // varies with `n`.
+    fn annotate_padding(&self, n: u64) -> sha1::Digest {
+        let padding = Commit::padding_for(n);
+        let mut m = sha1::Sha1::new();
+
+        m.update(format!("commit {}\0", self.length() + padding.len()).as_bytes());
+        m.update(self.metadata.as_slice());
+        m.update(b"\n\n");
+        m.update(self.message.as_slice());
+        m.update(padding.as_slice());
+
+        return m.digest();
+    }
+
+    // The exact byte stream that `annotate_padding` hashes.
+    fn object_bytes_padding(&self, n: u64) -> Vec<u8> {
+        let padding = Commit::padding_for(n);
+        let mut bytes = Vec::with_capacity(self.length() + padding.len());
+
+        bytes.extend_from_slice(format!("commit {}\0", self.length() + padding.len()).as_bytes());
+        bytes.extend_from_slice(self.metadata.as_slice());
+        bytes.extend_from_slice(b"\n\n");
+        bytes.extend_from_slice(self.message.as_slice());
+        bytes.extend_from_slice(padding.as_slice());
+
+        return bytes;
+    }
 }
 
 fn string_to_vec(string: &str) -> Vec<u8> {
@@ -151,6 +253,156 @@ committer Gunnar Þór Magnússon <gunnar.magnusson@booking.com> 1526714241 +020
         assert_eq!(c.annotate(100).to_string(), exp);
     }
 
+    #[test]
+    fn test_annotate_from_base_1() {
+        let c = Commit::new_with_prefix(
+            "tree 4ea62912d025c113066dab31e6135bd76277af91
+parent dfae4d199157e7f5c6b2f81cddb102215db12fa3
+author Gunnar Þór Magnússon <gunnar.magnusson@booking.com> 1526714241 +0200
+committer Gunnar Þór Magnússon <gunnar.magnusson@booking.com> 1526714241 +0200",
+            "Calculate sha1 of commits\n",
+            "gthm-id",
+        );
+
+        let base = c.base_hasher(100);
+        assert_eq!(
+            c.annotate_from_base(&base, 100).to_string(),
+            c.annotate(100).to_string()
+        );
+        assert_eq!(
+            c.annotate_from_base(&base, 999).to_string(),
+            c.annotate(999).to_string()
+        );
+    }
+
+    #[test]
+    fn test_target_prefix_1() {
+        let target = Target::Prefix(string_to_vec("000"));
+        assert_eq!(target.score("0012abc"), 2);
+        assert_eq!(target.score("000abc"), 3);
+        assert_eq!(target.goal(), 3);
+    }
+
+    #[test]
+    fn test_target_mask_1() {
+        let target = Target::Mask(string_to_vec("dead????beef"));
+        assert_eq!(target.score("dead1234beefcafe"), 12);
+        assert_eq!(target.score("dead1234bad0cafe"), 0);
+    }
+
+    #[test]
+    fn test_padding_for_1() {
+        assert_eq!(Commit::padding_for(0), string_to_vec(" "));
+        assert_eq!(Commit::padding_for(1), string_to_vec("\t"));
+        assert_eq!(Commit::padding_for(2), string_to_vec("  "));
+        assert_eq!(Commit::padding_for(3), string_to_vec(" \t"));
+        assert_eq!(Commit::padding_for(4), string_to_vec("\t "));
+        assert_eq!(Commit::padding_for(5), string_to_vec("\t\t"));
+        assert_eq!(Commit::padding_for(6), string_to_vec("   "));
+    }
+
+    #[test]
+    fn test_padding_for_dense_coverage() {
+        use std::collections::HashSet;
+
+        let seen: HashSet<Vec<u8>> = (2..6).map(Commit::padding_for).collect();
+
+        assert_eq!(seen.len(), 4);
+    }
+
+    #[test]
+    fn test_annotate_padding_1() {
+        let c = Commit::new("fooo", "barbar");
+        let without_padding = c.sha1().to_string();
+
+        assert_ne!(c.annotate_padding(0).to_string(), without_padding);
+        assert_ne!(
+            c.annotate_padding(0).to_string(),
+            c.annotate_padding(1).to_string()
+        );
+    }
+
+    // A scratch `.git`-shaped directory, unique per test name so parallel
+    // test runs don't collide.
+    fn fixture_git_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "git-commit-mine-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::create_dir_all(dir.join("refs/heads")).unwrap();
+        fs::write(dir.join("HEAD"), b"ref: refs/heads/master\n").unwrap();
+        fs::write(
+            dir.join("refs/heads/master"),
+            b"0000000000000000000000000000000000000000\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_mined_commit_1() {
+        use std::io::Read;
+
+        let dir = fixture_git_dir("success");
+        let c = Commit::new_with_prefix("tree abc123", "hello\n", "gthm-id");
+        let nugget = Nugget::new(7, 0);
+        let target = Target::Prefix(Vec::new());
+
+        let hex = write_mined_commit(&dir, &c, &nugget, Strategy::Nonce, &target).unwrap();
+        assert_eq!(hex, c.annotate(7).to_string());
+
+        let ref_contents = fs::read_to_string(dir.join("refs/heads/master")).unwrap();
+        assert_eq!(ref_contents.trim(), hex);
+
+        let (obj_dir, obj_file) = hex.split_at(2);
+        let compressed = fs::read(dir.join("objects").join(obj_dir).join(obj_file)).unwrap();
+        let mut decoder = flate2::read::ZlibDecoder::new(compressed.as_slice());
+        let mut stored = Vec::new();
+        decoder.read_to_end(&mut stored).unwrap();
+        assert_eq!(stored, c.object_bytes(7));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_mined_commit_refuses_short_of_goal() {
+        let dir = fixture_git_dir("short-of-goal");
+        let c = Commit::new_with_prefix("tree abc123", "hello\n", "gthm-id");
+        let nugget = Nugget::new(7, 2);
+        let target = Target::Prefix(string_to_vec("0000"));
+
+        let err = write_mined_commit(&dir, &c, &nugget, Strategy::Nonce, &target).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Nothing should have been written: no loose objects, ref untouched.
+        assert_eq!(fs::read_dir(dir.join("objects")).unwrap().count(), 0);
+        let ref_contents = fs::read_to_string(dir.join("refs/heads/master")).unwrap();
+        assert_eq!(
+            ref_contents.trim(),
+            "0000000000000000000000000000000000000000"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_head_ref_detached() {
+        let dir = fixture_git_dir("detached-head");
+        fs::write(
+            &dir.join("HEAD"),
+            b"0000000000000000000000000000000000000000\n",
+        )
+        .unwrap();
+
+        let err = resolve_head_ref(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_split_bytes_1() {
         let bs = string_to_vec("asdf\n\nqwer");
@@ -174,14 +426,156 @@ committer Gunnar Þór Magnússon <gunnar.magnusson@booking.com> 1526714241 +020
     }
 }
 
-fn count_zeros(hash: std::string::String) -> usize {
-    for (i, c) in hash.chars().enumerate() {
-        if c != '0' {
-            return i;
+// What a mined digest is scored against; `--zeros` is shorthand for an
+// all-'0' `Prefix`.
+#[derive(Clone, Debug)]
+enum Target {
+    // Leading hex characters that match the pattern ('?' always matches).
+    Prefix(Vec<u8>),
+    // An arbitrary mask anchored at the digest's start; scores as a
+    // boolean exact match, since partial credit means nothing here.
+    Mask(Vec<u8>),
+}
+
+impl Target {
+    fn from_opt(opt: &Opt) -> Target {
+        match opt.pattern {
+            Some(ref pattern) => Target::Mask(string_to_vec(pattern)),
+            None => Target::Prefix(vec![b'0'; opt.zeros]),
+        }
+    }
+
+    // Higher is better; `goal()` is the score that means "found".
+    fn score(&self, hash: &str) -> usize {
+        match *self {
+            Target::Prefix(ref pattern) => hash
+                .bytes()
+                .zip(pattern.iter())
+                .take_while(|&(h, &p)| p == b'?' || h == p)
+                .count(),
+            Target::Mask(ref pattern) => {
+                let matches = hash.len() >= pattern.len()
+                    && hash
+                        .bytes()
+                        .zip(pattern.iter())
+                        .all(|(h, &p)| p == b'?' || h == p);
+
+                if matches {
+                    pattern.len()
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    fn goal(&self) -> usize {
+        match *self {
+            Target::Prefix(ref pattern) => pattern.len(),
+            Target::Mask(ref pattern) => pattern.len(),
         }
     }
+}
+
+// Resolves the repo's git directory via `git rev-parse`, rather than
+// assuming the current directory is the repo root.
+fn resolve_git_dir() -> io::Result<PathBuf> {
+    let output = Command::new("git").arg("rev-parse").arg("--git-dir").output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("git rev-parse --git-dir failed"));
+    }
+
+    let dir = str::from_utf8(&output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .trim();
+
+    Ok(PathBuf::from(dir))
+}
+
+// Resolves `.git/HEAD` down to the ref it points at; a detached HEAD has
+// no branch to advance and is unsupported.
+fn resolve_head_ref(git_dir: &Path) -> io::Result<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD"))?;
+    let head = head.trim();
+
+    match head.trim_start_matches("ref: ") {
+        rest if rest != head => Ok(rest.to_string()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "HEAD is detached, refusing to guess which branch to advance",
+        )),
+    }
+}
 
-    return hash.len();
+// Zlib-deflates `bytes` into a loose object under `<git_dir>/objects/`.
+fn write_loose_object(git_dir: &Path, bytes: &[u8], digest: &sha1::Digest) -> io::Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    let compressed = encoder.finish()?;
+
+    let hex = digest.to_string();
+    let (dir, file) = hex.split_at(2);
+    let object_dir = git_dir.join("objects").join(dir);
+    fs::create_dir_all(&object_dir)?;
+    fs::write(object_dir.join(file), compressed)
+}
+
+// Writes the winning nonce into `git_dir`'s object store and advances
+// HEAD. Refuses unless `nugget` actually reached `target`'s goal, and
+// verifies the object hashes to `nugget`'s digest before moving the ref.
+fn write_mined_commit(
+    git_dir: &Path,
+    c: &Commit,
+    nugget: &Nugget,
+    strategy: Strategy,
+    target: &Target,
+) -> io::Result<String> {
+    if nugget.zeros < target.goal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "refusing to write: the best result did not reach the target, mining likely timed out",
+        ));
+    }
+
+    let (bytes, digest) = match strategy {
+        Strategy::Nonce => (c.object_bytes(nugget.nonce), c.annotate(nugget.nonce)),
+        Strategy::Padding => (
+            c.object_bytes_padding(nugget.nonce),
+            c.annotate_padding(nugget.nonce),
+        ),
+    };
+    if digest.to_string() != sha1::Sha1::from(&bytes).digest().to_string() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "mined digest does not match the recomputed object bytes",
+        ));
+    }
+
+    // Resolve the ref before writing the object, so a resolution failure
+    // can't leave an orphaned loose object behind.
+    let branch_ref = resolve_head_ref(git_dir)?;
+
+    write_loose_object(git_dir, &bytes, &digest)?;
+
+    let ref_path = git_dir.join(&branch_ref);
+    if let Some(parent) = ref_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&ref_path, format!("{}\n", digest))?;
+
+    Ok(digest.to_string())
+}
+
+// Resolves the process's git directory and delegates to `write_mined_commit`.
+fn write_commit_to_repo(
+    c: &Commit,
+    nugget: &Nugget,
+    strategy: Strategy,
+    target: &Target,
+) -> io::Result<String> {
+    let git_dir = resolve_git_dir()?;
+    write_mined_commit(&git_dir, c, nugget, strategy, target)
 }
 
 #[derive(StructOpt, Debug)]
@@ -191,12 +585,41 @@ struct Opt {
     timeout: u64,
     #[structopt(short = "z", long = "zeros", default_value = "6")]
     zeros: usize,
+    #[structopt(long = "pattern")]
+    pattern: Option<String>,
     #[structopt(long = "threads", default_value = "0")]
     threads: usize,
+    #[structopt(long = "write", alias = "amend")]
+    write: bool,
+    #[structopt(long = "serve")]
+    serve: Option<String>,
+    #[structopt(long = "connect")]
+    connect: Option<String>,
+    #[structopt(long = "padding")]
+    padding: bool,
     #[structopt(name = "PREFIX")]
     prefix: String,
 }
 
+// Which bytes get mutated: a visible nonce line, or invisible trailing
+// whitespace.
+#[derive(Clone, Copy)]
+enum Strategy {
+    Nonce,
+    Padding,
+}
+
+impl Strategy {
+    fn from_opt(opt: &Opt) -> Strategy {
+        if opt.padding {
+            Strategy::Padding
+        } else {
+            Strategy::Nonce
+        }
+    }
+}
+
+// A candidate nonce and its score against the active `Target`.
 #[derive(Eq, Copy, Clone)]
 struct Nugget {
     nonce: u64,
@@ -234,6 +657,240 @@ impl PartialEq for Nugget {
     }
 }
 
+// How many nonces a single lease hands out to a network worker; kept
+// small so a worker notices `Stop` soon after the goal is met elsewhere.
+const LEASE_BLOCK: u64 = 1 << 18;
+
+// A length-prefixed wire codec for the coordinator/worker protocol.
+mod wire {
+    use std::convert::TryInto;
+    use std::io::{self, Read, Write};
+
+    const TAG_LEASE_REQUEST: u8 = 1;
+    const TAG_LEASE_GRANT: u8 = 2;
+    const TAG_FOUND: u8 = 3;
+    const TAG_STOP: u8 = 4;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Message {
+        LeaseRequest,
+        LeaseGrant { start: u64, len: u64 },
+        Found { nonce: u64, zeros: usize },
+        Stop,
+    }
+
+    impl Message {
+        pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            let mut body = Vec::new();
+
+            match *self {
+                Message::LeaseRequest => body.push(TAG_LEASE_REQUEST),
+                Message::LeaseGrant { start, len } => {
+                    body.push(TAG_LEASE_GRANT);
+                    body.extend_from_slice(&start.to_be_bytes());
+                    body.extend_from_slice(&len.to_be_bytes());
+                }
+                Message::Found { nonce, zeros } => {
+                    body.push(TAG_FOUND);
+                    body.extend_from_slice(&nonce.to_be_bytes());
+                    body.extend_from_slice(&(zeros as u64).to_be_bytes());
+                }
+                Message::Stop => body.push(TAG_STOP),
+            }
+
+            w.write_all(&(body.len() as u32).to_be_bytes())?;
+            w.write_all(&body)
+        }
+
+        pub fn read_from<R: Read>(r: &mut R) -> io::Result<Message> {
+            let mut len_bytes = [0u8; 4];
+            r.read_exact(&mut len_bytes)?;
+
+            let mut body = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+            r.read_exact(&mut body)?;
+
+            let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed wire message");
+
+            match body.first() {
+                Some(&TAG_LEASE_REQUEST) => Ok(Message::LeaseRequest),
+                Some(&TAG_LEASE_GRANT) if body.len() == 17 => Ok(Message::LeaseGrant {
+                    start: u64::from_be_bytes(body[1..9].try_into().unwrap()),
+                    len: u64::from_be_bytes(body[9..17].try_into().unwrap()),
+                }),
+                Some(&TAG_FOUND) if body.len() == 17 => Ok(Message::Found {
+                    nonce: u64::from_be_bytes(body[1..9].try_into().unwrap()),
+                    zeros: u64::from_be_bytes(body[9..17].try_into().unwrap()) as usize,
+                }),
+                Some(&TAG_STOP) => Ok(Message::Stop),
+                _ => Err(malformed()),
+            }
+        }
+    }
+}
+
+// Leases `LEASE_BLOCK`-sized ranges from `cursor`, re-leasing once the
+// current range is exhausted; the same cursor network workers lease
+// from, keeping the local and remote search spaces disjoint.
+fn leased_nonce_source(cursor: Arc<AtomicU64>) -> impl FnMut() -> Option<u64> {
+    let mut remaining = 0..0u64;
+
+    move || {
+        if remaining.is_empty() {
+            let start = cursor.fetch_add(LEASE_BLOCK, AtomicOrdering::Relaxed);
+            remaining = start..start.saturating_add(LEASE_BLOCK);
+        }
+        remaining.next()
+    }
+}
+
+// Builds the digest function for whichever `Strategy` is active; the
+// nonce strategy reuses a cached SHA1 midstate per digit bucket, the
+// padding strategy just re-hashes from scratch each time.
+fn digest_fn_for(c: Commit, strategy: Strategy) -> Box<dyn FnMut(u64) -> String + Send> {
+    match strategy {
+        Strategy::Nonce => {
+            let mut bucket_len = 0;
+            let mut base = c.base_hasher(0);
+            Box::new(move |m| {
+                let len = base_10_length(m);
+                if len != bucket_len {
+                    base = c.base_hasher(m);
+                    bucket_len = len;
+                }
+                c.annotate_from_base(&base, m).to_string()
+            })
+        }
+        Strategy::Padding => Box::new(move |n| c.annotate_padding(n).to_string()),
+    }
+}
+
+// Shared mining loop for local threads and network workers: pulls
+// candidates from `next_nonce`, hashes with `digest_for`, and calls
+// `found` whenever a new local best appears.
+fn worker_core<N, F>(
+    target: &Target,
+    mut next_nonce: N,
+    digest_for: &mut dyn FnMut(u64) -> String,
+    mut found: F,
+) -> Nugget
+where
+    N: FnMut() -> Option<u64>,
+    F: FnMut(Nugget),
+{
+    let mut local_best = Nugget::new(0, 0);
+
+    while let Some(m) = next_nonce() {
+        let b = Nugget::new(m, target.score(&digest_for(m)));
+        if local_best.cmp(&b) == Ordering::Less {
+            local_best = b;
+            found(b);
+
+            // Nothing scored after the goal is met can beat it.
+            if b.zeros >= target.goal() {
+                break;
+            }
+        }
+    }
+
+    local_best
+}
+
+// Writes `Stop` to every peer; shared between `serve`'s own `Found`
+// handling and `main`, since either side can reach the goal first.
+fn broadcast_stop(peers: &Arc<Mutex<Vec<TcpStream>>>) {
+    for peer in peers.lock().unwrap().iter_mut() {
+        let _ = wire::Message::Stop.write_to(peer);
+    }
+}
+
+// Coordinator side of the distributed search: hands out disjoint nonce
+// ranges to connecting workers and forwards their `Found` results into
+// the same channel local threads report to.
+fn serve(
+    addr: &str,
+    goal: usize,
+    cursor: Arc<AtomicU64>,
+    peers: Arc<Mutex<Vec<TcpStream>>>,
+    results: Sender<Nugget>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        peers.lock().unwrap().push(stream.try_clone()?);
+
+        let cursor = Arc::clone(&cursor);
+        let peers = Arc::clone(&peers);
+        let results = Sender::clone(&results);
+
+        thread::spawn(move || loop {
+            let message = match wire::Message::read_from(&mut stream) {
+                Ok(m) => m,
+                Err(_) => return,
+            };
+
+            match message {
+                wire::Message::LeaseRequest => {
+                    let start = cursor.fetch_add(LEASE_BLOCK, AtomicOrdering::Relaxed);
+                    let grant = wire::Message::LeaseGrant {
+                        start: start,
+                        len: LEASE_BLOCK,
+                    };
+                    if grant.write_to(&mut stream).is_err() {
+                        return;
+                    }
+                }
+                wire::Message::Found { nonce, zeros } => {
+                    let _ = results.send(Nugget::new(nonce, zeros));
+
+                    if zeros >= goal {
+                        broadcast_stop(&peers);
+                    }
+                }
+                wire::Message::Stop | wire::Message::LeaseGrant { .. } => return,
+            }
+        });
+    }
+
+    Ok(())
+}
+
+// Worker side: leases nonce ranges from the coordinator and mines each
+// with `worker_core`, returning once `Stop` arrives or the connection drops.
+fn connect_and_mine(addr: &str, c: &Commit, target: &Target, strategy: Strategy) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut digest_for = digest_fn_for(c.clone(), strategy);
+
+    loop {
+        wire::Message::LeaseRequest.write_to(&mut stream)?;
+
+        let (start, len) = match wire::Message::read_from(&mut stream)? {
+            wire::Message::LeaseGrant { start, len } => (start, len),
+            wire::Message::Stop => return Ok(()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a lease grant from the coordinator",
+                ))
+            }
+        };
+
+        let mut remaining = start..start.saturating_add(len);
+        worker_core(
+            target,
+            || remaining.next(),
+            &mut *digest_for,
+            |b| {
+                let found = wire::Message::Found {
+                    nonce: b.nonce,
+                    zeros: b.zeros,
+                };
+                let _ = found.write_to(&mut stream);
+            },
+        );
+    }
+}
+
 fn main() {
     let opt = Opt::from_args();
 
@@ -251,6 +908,16 @@ fn main() {
         prefix: string_to_vec(&opt.prefix),
     };
 
+    let target = Target::from_opt(&opt);
+    let strategy = Strategy::from_opt(&opt);
+
+    if let Some(addr) = opt.connect {
+        if let Err(e) = connect_and_mine(&addr, &c, &target, strategy) {
+            eprintln!("Worker exiting: {}", e);
+        }
+        return;
+    }
+
     let start = Instant::now();
     let timeout = Duration::new(
         match opt.timeout {
@@ -266,25 +933,57 @@ fn main() {
     };
 
     let n = Arc::new(AtomicUsize::new(0));
+    let lease_cursor = Arc::new(AtomicU64::new(0));
+    let peers: Arc<Mutex<Vec<TcpStream>>> = Arc::new(Mutex::new(Vec::new()));
     let (sender, receiver) = channel();
 
+    let serving = opt.serve.is_some();
+
+    if let Some(addr) = opt.serve.clone() {
+        let results = Sender::clone(&sender);
+        let goal = target.goal();
+        let cursor = Arc::clone(&lease_cursor);
+        let peers = Arc::clone(&peers);
+
+        thread::spawn(move || {
+            if let Err(e) = serve(&addr, goal, cursor, peers, results) {
+                eprintln!("Coordinator exiting: {}", e);
+            }
+        });
+    }
+
     for _ in 0..threads {
         let n = Arc::clone(&n);
+        let lease_cursor = Arc::clone(&lease_cursor);
         let results = Sender::clone(&sender);
-        let c = c.clone();
+        let target = target.clone();
+        let mut digest_for = digest_fn_for(c.clone(), strategy);
 
         thread::spawn(move || {
-            let mut local_best = Nugget::new(0, 0);
-            loop {
-                // Ordering::Relaxed seems to be OK here according to:
-                // https://doc.rust-lang.org/nomicon/atomics.html
-                let m = n.fetch_add(1, AtomicOrdering::Relaxed) as u64;
-
-                let b = Nugget::new(m, count_zeros(c.annotate(m).to_string()));
-                if local_best.cmp(&b) == Ordering::Less {
-                    local_best = b;
-                    results.send(b).unwrap();
-                }
+            // Lease from the same cursor as network workers, keeping
+            // search spaces disjoint.
+            if serving {
+                worker_core(
+                    &target,
+                    leased_nonce_source(lease_cursor),
+                    &mut *digest_for,
+                    |b| {
+                        let _ = results.send(b);
+                    },
+                );
+            } else {
+                worker_core(
+                    &target,
+                    || {
+                        // Ordering::Relaxed seems to be OK here according to:
+                        // https://doc.rust-lang.org/nomicon/atomics.html
+                        Some(n.fetch_add(1, AtomicOrdering::Relaxed) as u64)
+                    },
+                    &mut *digest_for,
+                    |b| {
+                        let _ = results.send(b);
+                    },
+                );
             }
         });
     }
@@ -296,10 +995,25 @@ fn main() {
             println!("{}", best.string(&opt.prefix));
         }
 
-        if best.zeros >= opt.zeros || start.elapsed() > timeout {
+        if best.zeros >= target.goal() || start.elapsed() > timeout {
             break;
         }
     }
 
+    if serving && best.zeros >= target.goal() {
+        broadcast_stop(&peers);
+
+        // Give peers a moment to read the `Stop` before the process
+        // exit drops the socket out from under them.
+        thread::sleep(Duration::from_millis(200));
+    }
+
     println!("Best result: {}", best.string(&opt.prefix));
+
+    if opt.write {
+        match write_commit_to_repo(&c, &best, strategy, &target) {
+            Ok(hex) => println!("Wrote commit {} and advanced HEAD", hex),
+            Err(e) => eprintln!("Failed to write mined commit: {}", e),
+        }
+    }
 }